@@ -1,12 +1,21 @@
+use cmake::Config;
+use sha2::{Digest, Sha256};
 use std::env;
 use std::fs;
-use std::path::Path;
-use std::process::Command;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+// SHA-256 of liblexer-<target>.tar.gz per TARGET. Only add a triple once the
+// release job has published a real digest for it.
+const PREBUILT_SHA256: &[(&str, &str)] = &[];
+
+const DEFAULT_MIRROR: &str =
+    "https://github.com/OmarSiwy/ZYZVerilog/releases/download";
 
 fn main() {
     let out_dir = env::var("OUT_DIR").unwrap();
     let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
-    
+
     let dest_path = Path::new(&out_dir).join("lexer_info.rs");
     let build_info = format!(
         r#"pub const BUILD_TIME: &str = "{}";
@@ -18,51 +27,243 @@ pub const PKG_NAME: &str = "{}";
         env::var("CARGO_PKG_NAME").unwrap()
     );
     fs::write(&dest_path, build_info).unwrap();
-    
-    // Build C project using CMake + Ninja
+
+    // Build C project using CMake
     let project_root = Path::new(&manifest_dir);
-    let c_build_dir = project_root.join("build");
-    
+
     // Add rerun triggers for C project files
     println!("cargo:rerun-if-changed=CMakeLists.txt");
-    println!("cargo:rerun-if-changed=src/*.c");
-    println!("cargo:rerun-if-changed=inc/*.h");
     println!("cargo:rerun-if-changed=Cargo.toml");
-    println!("cargo:rerun-if-changed=src/");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=inc");
+    for path in c_source_files(project_root) {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
     println!("cargo:rustc-cfg=lexer_crate");
-    
+    println!("cargo:rerun-if-env-changed=DOCS_RS");
+    println!("cargo:rerun-if-env-changed=ZYZVERILOG_NATIVE_ARCHIVE");
+    println!("cargo:rerun-if-env-changed=ZYZVERILOG_FROM_SOURCE");
+    println!("cargo:rerun-if-env-changed=ZYZVERILOG_MIRROR");
+    println!("cargo:rerun-if-env-changed=SCCACHE");
+    println!("cargo:rerun-if-env-changed=CCACHE");
+
+    if should_skip_native_build() {
+        return;
+    }
+
+    if let Some(archive) = env::var_os("ZYZVERILOG_NATIVE_ARCHIVE") {
+        link_local_archive(Path::new(&out_dir), Path::new(&archive));
+        return;
+    }
+
+    let from_source = env::var_os("ZYZVERILOG_FROM_SOURCE").is_some();
+
+    if !from_source {
+        if let Ok(target) = env::var("TARGET") {
+            if try_download_prebuilt(Path::new(&out_dir), &target) {
+                return;
+            }
+        }
+    }
+
+    if !from_source && try_pkg_config() {
+        return;
+    }
+
     // Check if CMakeLists.txt exists
     let cmake_file = project_root.join("CMakeLists.txt");
     if cmake_file.exists() {
-        // Create build directory if it doesn't exist
-        std::fs::create_dir_all(&c_build_dir).expect("Failed to create build directory");
-        
-        // Run CMake to generate Ninja build files
-        let cmake_status = Command::new("cmake")
-            .current_dir(&c_build_dir)
-            .arg("-G")
-            .arg("Ninja")
-            .arg("-DCMAKE_BUILD_TYPE=Release")
-            .arg("..")
-            .status()
-            .expect("Failed to execute cmake. Make sure CMake is installed.");
-        
-        if !cmake_status.success() {
-            panic!("CMake configuration failed");
-        }
-        
-        // Build with Ninja
-        let ninja_status = Command::new("ninja")
-            .current_dir(&c_build_dir)
-            .status()
-            .expect("Failed to execute ninja. Make sure Ninja is installed.");
-        
-        if !ninja_status.success() {
-            panic!("Ninja build failed");
+        build_from_source(project_root);
+    }
+}
+
+fn try_pkg_config() -> bool {
+    let min_version = env::var("CARGO_PKG_VERSION").unwrap_or_else(|_| "0.0.0".to_string());
+    pkg_config::Config::new()
+        .atleast_version(&min_version)
+        .statik(true)
+        .probe("zyzverilog-lexer")
+        .is_ok()
+}
+
+fn should_skip_native_build() -> bool {
+    if env::var_os("DOCS_RS").is_some() {
+        return true;
+    }
+
+    if let Some(cargo) = env::var_os("CARGO") {
+        let stem = Path::new(&cargo)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("");
+        if stem.starts_with("rls") || stem.starts_with("rust-analyzer") {
+            return true;
         }
-        
-        // Tell Cargo where to find the library and link against c library
-        println!("cargo:rustc-link-search=native={}", c_build_dir.display());
+    }
+
+    false
+}
+
+fn link_local_archive(out_dir: &Path, archive: &Path) {
+    if archive.extension().map_or(false, |ext| ext == "a") {
+        let lib_name = lib_name_from_archive(archive);
+        let dest = out_dir.join("native-archive");
+        fs::create_dir_all(&dest).expect("failed to create ZYZVERILOG_NATIVE_ARCHIVE staging dir");
+        fs::copy(archive, dest.join(format!("lib{lib_name}.a")))
+            .expect("failed to stage ZYZVERILOG_NATIVE_ARCHIVE under its expected name");
+        println!("cargo:rustc-link-search=native={}", dest.display());
+        println!("cargo:rustc-link-lib=static={}", lib_name);
+    } else {
+        let dest = out_dir.join("native-archive");
+        extract_tar_gz(archive, &dest).expect("failed to extract ZYZVERILOG_NATIVE_ARCHIVE");
+        println!("cargo:rustc-link-search=native={}", dest.display());
         println!("cargo:rustc-link-lib=static=native");
     }
 }
+
+// Derives the -l name from a .a path, e.g. libfoo.a -> foo, mylexer.a -> mylexer.
+fn lib_name_from_archive(archive: &Path) -> String {
+    let stem = archive.file_stem().and_then(|s| s.to_str()).unwrap_or("native");
+    stem.strip_prefix("lib").unwrap_or(stem).to_string()
+}
+
+fn try_download_prebuilt(out_dir: &Path, target: &str) -> bool {
+    let Some(expected_sha256) = PREBUILT_SHA256
+        .iter()
+        .find(|(triple, _)| *triple == target)
+        .map(|(_, sha)| *sha)
+    else {
+        return false;
+    };
+
+    let mirror = env::var("ZYZVERILOG_MIRROR").unwrap_or_else(|_| DEFAULT_MIRROR.to_string());
+    let version = env::var("CARGO_PKG_VERSION").unwrap();
+    let url = format!("{mirror}/v{version}/liblexer-{target}.tar.gz");
+
+    let archive_path = out_dir.join(format!("liblexer-{target}.tar.gz"));
+    if download(&url, &archive_path).is_err() {
+        return false;
+    }
+
+    if !verify_sha256(&archive_path, expected_sha256) {
+        panic!("checksum mismatch for prebuilt lexer archive downloaded from {url}");
+    }
+
+    let dest = out_dir.join("prebuilt");
+    extract_tar_gz(&archive_path, &dest).expect("failed to extract prebuilt lexer archive");
+
+    println!("cargo:rustc-link-search=native={}", dest.display());
+    println!("cargo:rustc-link-lib=static=native");
+    true
+}
+
+fn download(url: &str, dest: &Path) -> Result<(), ureq::Error> {
+    let mut reader = ureq::get(url).call()?.into_reader();
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).map_err(ureq::Error::from)?;
+    fs::write(dest, bytes).expect("failed to write downloaded archive");
+    Ok(())
+}
+
+fn verify_sha256(path: &Path, expected_hex: &str) -> bool {
+    let bytes = fs::read(path).expect("failed to read downloaded archive for checksum");
+    let mut hasher = Sha256::new();
+    hasher.update(&bytes);
+    let digest = hasher.finalize();
+    let actual_hex = digest.iter().map(|b| format!("{b:02x}")).collect::<String>();
+    actual_hex == expected_hex
+}
+
+fn extract_tar_gz(archive: &Path, dest: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dest)?;
+    let file = fs::File::open(archive)?;
+    let decoder = flate2::read::GzDecoder::new(file);
+    tar::Archive::new(decoder).unpack(dest)
+}
+
+fn build_from_source(project_root: &Path) {
+    let profile = env::var("PROFILE").unwrap_or_else(|_| "release".to_string());
+    let cmake_build_type = match profile.as_str() {
+        "debug" => "Debug",
+        _ => "Release",
+    };
+
+    let mut config = Config::new(project_root);
+    config
+        .generator("Ninja")
+        .no_build_target(true)
+        .define("CMAKE_BUILD_TYPE", cmake_build_type);
+
+    if let Ok(toolchain_file) = env::var("CMAKE_TOOLCHAIN_FILE") {
+        config.define("CMAKE_TOOLCHAIN_FILE", toolchain_file);
+    }
+    if let Ok(cc) = env::var("CC") {
+        config.define("CMAKE_C_COMPILER", cc);
+    }
+    if let Ok(cxx) = env::var("CXX") {
+        config.define("CMAKE_CXX_COMPILER", cxx);
+    }
+    if let Ok(jobs) = env::var("NUM_JOBS") {
+        config.build_arg(format!("-j{}", jobs));
+    }
+    if let Some(launcher) = compiler_launcher() {
+        config.define("CMAKE_C_COMPILER_LAUNCHER", launcher);
+    }
+
+    let dst = config.build();
+
+    // Tell Cargo where to find the library and link against c library
+    println!("cargo:rustc-link-search=native={}", dst.join("build").display());
+    println!("cargo:rustc-link-lib=static=native");
+}
+
+fn c_source_files(project_root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for dir in ["src", "inc"] {
+        walk_dir(&project_root.join(dir), &mut files);
+    }
+    files
+}
+
+fn walk_dir(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk_dir(&path, files);
+        } else if is_tracked_source_file(&path) {
+            files.push(path);
+        }
+    }
+}
+
+fn is_tracked_source_file(path: &Path) -> bool {
+    if path.file_name().and_then(|name| name.to_str()) == Some("CMakeLists.txt") {
+        return true;
+    }
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("c") | Some("h") | Some("cmake")
+    )
+}
+
+fn compiler_launcher() -> Option<String> {
+    if let Ok(path) = env::var("SCCACHE") {
+        return Some(path);
+    }
+    if let Ok(path) = env::var("CCACHE") {
+        return Some(path);
+    }
+    find_on_path("sccache").or_else(|| find_on_path("ccache"))
+}
+
+fn find_on_path(name: &str) -> Option<String> {
+    let path_var = env::var_os("PATH")?;
+    env::split_paths(&path_var)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+        .map(|path| path.display().to_string())
+}
+